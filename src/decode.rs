@@ -0,0 +1,491 @@
+//! Incremental protocol decoders that turn sampled digital lines into the
+//! human-readable transaction strings shown by `Header::add_protocol` vars.
+use crate::vcd::{DumpVars, Variable};
+use std::io::{Result, Write};
+use vcd::Value;
+
+/// A resettable, sample-at-a-time protocol decoder.
+///
+/// `feed` is called once per sample with the current timestamp and the
+/// values of the lines the decoder was constructed to watch. It returns
+/// `Some(text)` only when the decoded transaction text actually changes,
+/// so callers can drive a `Variable<String>` without emitting redundant
+/// VCD changes.
+pub trait Decoder {
+    fn feed(&mut self, t: u64, values: &[Value]) -> Option<String>;
+}
+
+fn is_high(v: Value) -> bool {
+    v == Value::V1
+}
+
+/// Drives a `Variable<String>` from a [`Decoder`] fed with a group of
+/// digital inputs, writing a VCD change whenever the decoder emits text.
+pub struct Bus<D> {
+    decoder: D,
+    output: Variable<String>,
+}
+
+impl<D: Decoder> Bus<D> {
+    pub fn new(decoder: D, output: Variable<String>) -> Self {
+        Self { decoder, output }
+    }
+
+    pub fn sample<W: Write>(
+        &mut self,
+        dump: &mut DumpVars<W>,
+        t: u64,
+        values: &[Value],
+    ) -> Result<()> {
+        if let Some(text) = self.decoder.feed(t, values) {
+            dump.timestamp(t)?;
+            dump.change_value(&self.output, &text)?;
+        }
+        Ok(())
+    }
+}
+
+/// I2C decoder. Expects `values` as `[scl, sda]`.
+#[derive(Default)]
+pub struct I2c {
+    scl: Option<Value>,
+    sda: Option<Value>,
+    phase: I2cPhase,
+    is_address_byte: bool,
+    last: Option<String>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum I2cPhase {
+    Idle,
+    Byte { bits: u8, value: u8 },
+    Ack { byte: u8 },
+}
+
+impl Default for I2cPhase {
+    fn default() -> Self {
+        I2cPhase::Idle
+    }
+}
+
+impl I2c {
+    pub fn new() -> Self {
+        Self {
+            is_address_byte: true,
+            ..Default::default()
+        }
+    }
+
+    fn emit(&mut self, text: String) -> Option<String> {
+        if self.last.as_deref() != Some(text.as_str()) {
+            self.last = Some(text.clone());
+            Some(text)
+        } else {
+            None
+        }
+    }
+}
+
+impl Decoder for I2c {
+    fn feed(&mut self, _t: u64, values: &[Value]) -> Option<String> {
+        let scl = values[0];
+        let sda = values[1];
+        let (prev_scl, prev_sda) = match (self.scl, self.sda) {
+            (Some(scl), Some(sda)) => (scl, sda),
+            _ => {
+                self.scl = Some(scl);
+                self.sda = Some(sda);
+                return None;
+            }
+        };
+        let scl_rising = !is_high(prev_scl) && is_high(scl);
+        let sda_falling = is_high(prev_sda) && !is_high(sda);
+        let sda_rising = !is_high(prev_sda) && is_high(sda);
+        self.scl = Some(scl);
+        self.sda = Some(sda);
+
+        // A SDA transition while SCL is high is only legal as a start or
+        // stop condition; seeing one mid-byte is exactly the "unexpected
+        // edge" glitch case, so it always restarts the bit counter below.
+        if is_high(scl) && sda_falling {
+            self.phase = I2cPhase::Byte { bits: 0, value: 0 };
+            self.is_address_byte = true;
+            return self.emit("start".into());
+        }
+        if is_high(scl) && sda_rising {
+            self.phase = I2cPhase::Idle;
+            return self.emit("stop".into());
+        }
+        if !scl_rising {
+            return None;
+        }
+        match self.phase {
+            I2cPhase::Byte { bits, value } if bits < 8 => {
+                let value = (value << 1) | if is_high(sda) { 1 } else { 0 };
+                let bits = bits + 1;
+                self.phase = if bits == 8 {
+                    I2cPhase::Ack { byte: value }
+                } else {
+                    I2cPhase::Byte { bits, value }
+                };
+                None
+            }
+            I2cPhase::Ack { byte } => {
+                let ack = if is_high(sda) { "nack" } else { "ack" };
+                let text = if self.is_address_byte {
+                    self.is_address_byte = false;
+                    let rw = if byte & 1 == 1 { "read" } else { "write" };
+                    format!("{} 0x{:02X}", rw, byte >> 1)
+                } else {
+                    format!("data 0x{:02X} {}", byte, ack)
+                };
+                self.phase = I2cPhase::Byte { bits: 0, value: 0 };
+                self.emit(text)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// SPI decoder (mode 0: sample MOSI/MISO on SCK rising edge, CS active low).
+/// Expects `values` as `[sck, mosi, miso, cs]`.
+#[derive(Default)]
+pub struct Spi {
+    sck: Option<Value>,
+    cs: Option<Value>,
+    bits: u8,
+    mosi_byte: u8,
+    miso_byte: u8,
+    last: Option<String>,
+}
+
+impl Spi {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn emit(&mut self, text: String) -> Option<String> {
+        if self.last.as_deref() != Some(text.as_str()) {
+            self.last = Some(text.clone());
+            Some(text)
+        } else {
+            None
+        }
+    }
+}
+
+impl Decoder for Spi {
+    fn feed(&mut self, _t: u64, values: &[Value]) -> Option<String> {
+        let sck = values[0];
+        let mosi = values[1];
+        let miso = values[2];
+        let cs = values[3];
+        let (prev_sck, prev_cs) = match (self.sck, self.cs) {
+            (Some(sck), Some(cs)) => (sck, cs),
+            _ => {
+                self.sck = Some(sck);
+                self.cs = Some(cs);
+                return None;
+            }
+        };
+        let sck_rising = !is_high(prev_sck) && is_high(sck);
+        let cs_falling = is_high(prev_cs) && !is_high(cs);
+        let cs_rising = !is_high(prev_cs) && is_high(cs);
+        self.sck = Some(sck);
+        self.cs = Some(cs);
+
+        if cs_falling {
+            self.bits = 0;
+            self.mosi_byte = 0;
+            self.miso_byte = 0;
+            return self.emit("select".into());
+        }
+        if cs_rising {
+            return self.emit("deselect".into());
+        }
+        if is_high(cs) || !sck_rising {
+            return None;
+        }
+        self.mosi_byte = (self.mosi_byte << 1) | if is_high(mosi) { 1 } else { 0 };
+        self.miso_byte = (self.miso_byte << 1) | if is_high(miso) { 1 } else { 0 };
+        self.bits += 1;
+        if self.bits < 8 {
+            return None;
+        }
+        let text = format!("mosi 0x{:02X} miso 0x{:02X}", self.mosi_byte, self.miso_byte);
+        self.bits = 0;
+        self.mosi_byte = 0;
+        self.miso_byte = 0;
+        self.emit(text)
+    }
+}
+
+/// UART decoder, 8N1, no clock line: resynchronizes on every start bit.
+/// Expects `values` as `[rx]` and `bit_period` in the same time units as
+/// the timestamps passed to `feed`.
+pub struct Uart {
+    bit_period: u64,
+    state: UartState,
+    last: Option<String>,
+}
+
+enum UartState {
+    Idle,
+    Data { start: u64, bits: u8, value: u8 },
+}
+
+impl Uart {
+    pub fn new(bit_period: u64) -> Self {
+        Self {
+            bit_period,
+            state: UartState::Idle,
+            last: None,
+        }
+    }
+
+    fn emit(&mut self, text: String) -> Option<String> {
+        if self.last.as_deref() != Some(text.as_str()) {
+            self.last = Some(text.clone());
+            Some(text)
+        } else {
+            None
+        }
+    }
+}
+
+impl Decoder for Uart {
+    fn feed(&mut self, t: u64, values: &[Value]) -> Option<String> {
+        let rx = values[0];
+        match self.state {
+            UartState::Idle => {
+                if !is_high(rx) {
+                    self.state = UartState::Data {
+                        start: t,
+                        bits: 0,
+                        value: 0,
+                    };
+                }
+                None
+            }
+            UartState::Data { start, bits, value } => {
+                let elapsed = t.saturating_sub(start);
+                let bit_index = elapsed / self.bit_period;
+                if bit_index <= bits as u64 {
+                    return None;
+                }
+                if bits < 8 {
+                    let value = value | (if is_high(rx) { 1 } else { 0 }) << bits;
+                    self.state = UartState::Data {
+                        start,
+                        bits: bits + 1,
+                        value,
+                    };
+                    None
+                } else {
+                    // Stop bit: expect idle-high; treat a low stop bit as a
+                    // framing error and resynchronize on the next start bit.
+                    self.state = UartState::Idle;
+                    if is_high(rx) {
+                        self.emit(format!("data 0x{:02X}", value))
+                    } else {
+                        self.emit("framing error".into())
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vcd::{Header, Timescale};
+    use std::sync::{Arc, Mutex};
+
+    fn clock_byte(dec: &mut I2c, byte: u8) {
+        for i in (0..8).rev() {
+            let bit = if (byte >> i) & 1 == 1 { Value::V1 } else { Value::V0 };
+            dec.feed(0, &[Value::V0, bit]); // SCL low: data changes here
+            dec.feed(0, &[Value::V1, bit]); // SCL rising: bit sampled
+        }
+    }
+
+    fn clock_ack(dec: &mut I2c, ack: bool) -> Option<String> {
+        let sda = if ack { Value::V0 } else { Value::V1 };
+        dec.feed(0, &[Value::V0, sda]);
+        dec.feed(0, &[Value::V1, sda])
+    }
+
+    #[test]
+    fn i2c_decodes_start_address_data_ack_stop() {
+        let mut dec = I2c::new();
+        assert_eq!(dec.feed(0, &[Value::V1, Value::V1]), None);
+
+        // START: SDA falls while SCL is high.
+        assert_eq!(dec.feed(1, &[Value::V1, Value::V0]), Some("start".into()));
+
+        clock_byte(&mut dec, 0xA0); // address 0x50, write
+        assert_eq!(clock_ack(&mut dec, true), Some("write 0x50".into()));
+
+        clock_byte(&mut dec, 0xAB);
+        assert_eq!(clock_ack(&mut dec, true), Some("data 0xAB ack".into()));
+
+        // STOP: SDA rises while SCL stays high.
+        assert_eq!(dec.feed(2, &[Value::V1, Value::V1]), Some("stop".into()));
+    }
+
+    #[test]
+    fn i2c_resyncs_bit_counter_on_glitch_mid_byte() {
+        let mut dec = I2c::new();
+        dec.feed(0, &[Value::V1, Value::V1]);
+        assert_eq!(dec.feed(1, &[Value::V1, Value::V0]), Some("start".into()));
+
+        // Clock 3 stray bits, then an SDA edge while SCL is high lands
+        // mid-byte -- the decoder treats it like a fresh start (the only
+        // legal reading of that edge) and must restart the bit counter
+        // rather than splicing it onto the partial byte.
+        for bit in [Value::V1, Value::V0, Value::V1] {
+            dec.feed(0, &[Value::V0, bit]);
+            dec.feed(0, &[Value::V1, bit]);
+        }
+        dec.feed(0, &[Value::V1, Value::V0]); // glitch: SDA falls while SCL high
+
+        clock_byte(&mut dec, 0xA0);
+        assert_eq!(clock_ack(&mut dec, true), Some("write 0x50".into()));
+    }
+
+    #[test]
+    fn i2c_suppresses_repeated_identical_decoded_text() {
+        let mut dec = I2c::new();
+        dec.feed(0, &[Value::V1, Value::V1]);
+        dec.feed(1, &[Value::V1, Value::V0]); // start
+        clock_byte(&mut dec, 0xA0);
+        clock_ack(&mut dec, true);
+
+        clock_byte(&mut dec, 0xAB);
+        assert_eq!(clock_ack(&mut dec, true), Some("data 0xAB ack".into()));
+
+        // Same byte and ack again: the decoded text is unchanged, so no
+        // redundant VCD change should be emitted.
+        clock_byte(&mut dec, 0xAB);
+        assert_eq!(clock_ack(&mut dec, true), None);
+    }
+
+    #[test]
+    fn spi_decodes_select_byte_deselect() {
+        let mut dec = Spi::new();
+        assert_eq!(
+            dec.feed(0, &[Value::V0, Value::V0, Value::V0, Value::V1]),
+            None
+        );
+
+        // CS falls: select.
+        assert_eq!(
+            dec.feed(1, &[Value::V0, Value::V0, Value::V0, Value::V0]),
+            Some("select".into())
+        );
+
+        // Clock one byte, MOSI=0x3C and MISO=0x81 MSB first, sampled on
+        // SCK's rising edge.
+        let (mosi, miso) = (0x3Cu8, 0x81u8);
+        let mut last = None;
+        for i in (0..8).rev() {
+            let mbit = if (mosi >> i) & 1 == 1 { Value::V1 } else { Value::V0 };
+            let sbit = if (miso >> i) & 1 == 1 { Value::V1 } else { Value::V0 };
+            dec.feed(0, &[Value::V0, mbit, sbit, Value::V0]);
+            last = dec.feed(0, &[Value::V1, mbit, sbit, Value::V0]);
+        }
+        assert_eq!(last, Some("mosi 0x3C miso 0x81".into()));
+
+        // CS rises: deselect.
+        assert_eq!(
+            dec.feed(2, &[Value::V0, Value::V0, Value::V0, Value::V1]),
+            Some("deselect".into())
+        );
+    }
+
+    #[test]
+    fn uart_decodes_data_byte_lsb_first() {
+        let bit_period = 10;
+        let mut dec = Uart::new(bit_period);
+        let value = 0x55u8;
+
+        assert_eq!(dec.feed(0, &[Value::V1]), None); // idle high
+        let start = 100;
+        assert_eq!(dec.feed(start, &[Value::V0]), None); // start bit
+
+        for i in 0..8u64 {
+            let bit = if (value >> i) & 1 == 1 { Value::V1 } else { Value::V0 };
+            let t = start + (i + 1) * bit_period;
+            assert_eq!(dec.feed(t, &[bit]), None);
+        }
+
+        let stop_t = start + 9 * bit_period;
+        assert_eq!(dec.feed(stop_t, &[Value::V1]), Some("data 0x55".into()));
+    }
+
+    #[test]
+    fn uart_reports_framing_error_and_resyncs_on_next_start_bit() {
+        let bit_period = 10;
+        let mut dec = Uart::new(bit_period);
+        dec.feed(0, &[Value::V1]);
+        let start = 100;
+        dec.feed(start, &[Value::V0]);
+        for i in 0..8u64 {
+            let t = start + (i + 1) * bit_period;
+            dec.feed(t, &[Value::V0]); // all-zero data byte
+        }
+
+        let stop_t = start + 9 * bit_period;
+        // Stop bit low instead of high: framing error.
+        assert_eq!(
+            dec.feed(stop_t, &[Value::V0]),
+            Some("framing error".into())
+        );
+
+        // A later start bit resyncs cleanly.
+        let start2 = stop_t + bit_period;
+        assert_eq!(dec.feed(start2, &[Value::V0]), None);
+        for i in 0..8u64 {
+            let t = start2 + (i + 1) * bit_period;
+            let bit = if i == 0 { Value::V1 } else { Value::V0 };
+            dec.feed(t, &[bit]);
+        }
+        let stop2 = start2 + 9 * bit_period;
+        assert_eq!(dec.feed(stop2, &[Value::V1]), Some("data 0x01".into()));
+    }
+
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bus_only_timestamps_and_changes_when_decoder_emits_text() {
+        let sink = Capture::default();
+        let mut header = Header::new(sink.clone(), Timescale::us(1)).unwrap();
+        header.start_module("bus").unwrap();
+        let output = header.add_protocol("i2c").unwrap();
+        header.end_module().unwrap();
+        let mut dump = header.finish().unwrap();
+        let mut bus = Bus::new(I2c::new(), output);
+
+        bus.sample(&mut dump, 1, &[Value::V1, Value::V1]).unwrap(); // priming edge, no decode yet
+        bus.sample(&mut dump, 2, &[Value::V1, Value::V0]).unwrap(); // START: decoder emits "start"
+        dump.finish().unwrap();
+
+        let text = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        assert!(text.contains("#2\n"));
+        assert!(!text.contains("#1\n"));
+        assert!(text.contains("start"));
+    }
+}
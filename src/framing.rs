@@ -0,0 +1,115 @@
+//! Length-prefixed, CRC-32-checked record framing for long unattended
+//! captures: `[u32 length][payload][u32 crc]` per record, so a truncated or
+//! corrupted file can be detected and resynchronized past instead of
+//! aborting the whole read.
+use std::io::{self, Read, Write};
+
+/// Records longer than this are treated as an implausible length prefix
+/// (almost certainly a misaligned read) rather than awaited in full.
+const MAX_RECORD_LEN: usize = 16 * 1024 * 1024;
+
+fn crc32(data: &[u8]) -> u32 {
+    const POLY: u32 = 0xEDB8_8320;
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = 0u32.wrapping_sub(crc & 1);
+            crc = (crc >> 1) ^ (POLY & mask);
+        }
+    }
+    !crc
+}
+
+/// Frames records onto an underlying sink as they're produced.
+pub struct Writer<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner }
+    }
+
+    pub fn write_record(&mut self, payload: &[u8]) -> io::Result<()> {
+        self.inner.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.inner.write_all(payload)?;
+        self.inner.write_all(&crc32(payload).to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads framed records, resynchronizing past corrupt regions instead of
+/// failing the whole stream.
+pub struct Reader<R: Read> {
+    inner: R,
+    buf: Vec<u8>,
+    /// Records that read and checksummed cleanly.
+    pub valid: usize,
+    /// Distinct corrupt regions encountered (not every byte slid past).
+    pub corrupt: usize,
+    /// Of `corrupt`, the number resynchronization recovered a later record.
+    pub recovered: usize,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            valid: 0,
+            corrupt: 0,
+            recovered: 0,
+        }
+    }
+
+    /// Reads the next valid record, or `Ok(None)` at end of stream. A
+    /// truncated final record is counted in `corrupt` and skipped.
+    pub fn read_record(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut resyncing = false;
+        loop {
+            if !self.fill(4)? {
+                return Ok(None);
+            }
+            let len = u32::from_le_bytes(self.buf[0..4].try_into().unwrap()) as usize;
+            let total = 4 + len + 4;
+            let framed = len <= MAX_RECORD_LEN && self.fill(total)?;
+            let checksum_ok = framed
+                && crc32(&self.buf[4..4 + len])
+                    == u32::from_le_bytes(self.buf[4 + len..total].try_into().unwrap());
+            if checksum_ok {
+                let payload = self.buf[4..4 + len].to_vec();
+                self.buf.drain(..total);
+                self.valid += 1;
+                if resyncing {
+                    self.recovered += 1;
+                }
+                return Ok(Some(payload));
+            }
+            if !resyncing {
+                self.corrupt += 1;
+                resyncing = true;
+            }
+            // Slide one byte forward and reinterpret from there, the
+            // standard resync strategy for a framing that isn't otherwise
+            // self-delimiting.
+            self.buf.drain(..1);
+        }
+    }
+
+    fn fill(&mut self, need: usize) -> io::Result<bool> {
+        let mut chunk = [0u8; 4096];
+        while self.buf.len() < need {
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                return Ok(false);
+            }
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+        Ok(true)
+    }
+}
@@ -0,0 +1,6 @@
+pub mod decode;
+pub mod framing;
+pub mod ring;
+pub mod serve;
+pub mod summary;
+pub mod vcd;
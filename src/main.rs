@@ -1,18 +1,43 @@
 use anyhow::Result;
 use async_io::Async;
+use daq::framing;
+use daq::ring::{ring, Reader};
+use daq::serve::{serve, Hub, Tee};
+use daq::summary::summarize;
+use daq::vcd::{bits, DumpVars, Header, Timescale, Variable};
 use futures::future::FutureExt;
 use futures::stream::StreamExt;
 use pcap::{Capture, Device, Error};
+use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+/// Bytes of backing storage for the ring buffer that hands packet records
+/// from the capture callback to the VCD writer task.
+const VCD_RING_CAPACITY: usize = 1 << 20;
+
 #[derive(StructOpt)]
 struct Opts {
     #[structopt(short, long, conflicts_with("output"))]
     input: Option<PathBuf>,
     #[structopt(short, long, conflicts_with("input"))]
     output: Option<PathBuf>,
+    #[structopt(long)]
+    vcd: Option<PathBuf>,
+    #[structopt(long)]
+    serve: Option<SocketAddr>,
+    /// Archive every captured packet as a CRC-32-framed record, so a long
+    /// unattended capture can be checked for corruption and recovered past
+    /// it afterwards instead of losing the whole file.
+    #[structopt(long)]
+    framed: Option<PathBuf>,
+    /// Read a `--framed` archive back, reporting how many records were
+    /// valid, corrupt, and recovered, instead of capturing.
+    #[structopt(long, conflicts_with_all(&["input", "output", "device"]))]
+    verify: Option<PathBuf>,
     #[structopt(short, long)]
     device: Option<String>,
     #[structopt(short, long, default_value = "1000000")]
@@ -29,11 +54,103 @@ struct Opts {
     verbose: bool,
 }
 
+/// A sink that can either persist to disk, live-stream to `--serve`
+/// clients, or both at once, chosen at startup based on which flags were
+/// passed.
+type Sink = Box<dyn Write + Send>;
+
+/// Mirrors captured packets into a VCD waveform so a `.pcap` session can be
+/// opened in any VCD viewer: packet length as a vector, whether the frame's
+/// destination address is broadcast/multicast as a digital bit, and a
+/// decoded one-line summary as a protocol string.
+///
+/// The digital bit is the link layer's I/G (individual/group) destination
+/// address bit rather than true per-packet capture direction: this crate's
+/// `pcap::PacketHeader` only carries a timestamp and lengths, not the
+/// `PCAP_D_IN`/`PCAP_D_OUT` direction pcap can filter on but doesn't attach
+/// to each packet, so broadcast/multicast-vs-unicast is the closest
+/// per-packet link-layer signal actually available here.
+struct VcdSink {
+    dump: DumpVars<Sink>,
+    len: Variable<Vec<vcd::Value>>,
+    bcast: Variable<vcd::Value>,
+    summary: Variable<String>,
+    last_t: u64,
+}
+
+impl VcdSink {
+    fn open(sink: Sink) -> Result<Self> {
+        let mut header = Header::new(sink, Timescale::us(1))?;
+        header.start_module("capture")?;
+        let len = header.add_vector("len", 16)?;
+        let bcast = header.add_digital("bcast")?;
+        let summary = header.add_protocol("summary")?;
+        header.end_module()?;
+        Ok(Self {
+            dump: header.finish()?,
+            len,
+            bcast,
+            summary,
+            last_t: 0,
+        })
+    }
+
+    // Packet timestamps arrive as pcap microseconds; out-of-order or
+    // duplicate timestamps are coalesced onto the current tick instead of
+    // emitting a new `$timestamp`, since VCD requires non-decreasing ones.
+    fn record(&mut self, t_us: u64, len: u32, data: &[u8]) -> Result<()> {
+        let t = t_us.max(self.last_t);
+        if t != self.last_t {
+            self.dump.timestamp(t)?;
+            self.last_t = t;
+        }
+        self.dump.change_value(&self.len, &bits(len as u64, 16))?;
+        let is_bcast = data.len() >= 6 && data[0] & 1 == 1;
+        self.dump.change_value(&self.bcast, &is_bcast.into())?;
+        self.dump.change_value(&self.summary, &summarize(data))?;
+        Ok(())
+    }
+
+    /// Drains encoded records from `reader`, decoding and writing each one,
+    /// until the ring's writer half is dropped and fully drained.
+    async fn run(mut self, mut reader: Reader) -> Result<()> {
+        while let Some(record) = reader.recv().await {
+            let (t_us, len, data) = decode_record(&record);
+            self.record(t_us, len, data)?;
+        }
+        self.dump.finish()?;
+        Ok(())
+    }
+}
+
+fn encode_record(t_us: u64, len: u32, data: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(12 + data.len());
+    buf.extend_from_slice(&t_us.to_le_bytes());
+    buf.extend_from_slice(&len.to_le_bytes());
+    buf.extend_from_slice(data);
+    buf
+}
+
+fn decode_record(record: &[u8]) -> (u64, u32, &[u8]) {
+    let t_us = u64::from_le_bytes(record[0..8].try_into().unwrap());
+    let len = u32::from_le_bytes(record[8..12].try_into().unwrap());
+    (t_us, len, &record[12..])
+}
+
 #[async_std::main]
 async fn main() -> Result<()> {
     env_logger::init();
     let opts = Opts::from_args();
 
+    if let Some(path) = opts.verify.as_ref() {
+        let mut reader = framing::Reader::new(std::io::BufReader::new(File::open(path)?));
+        while reader.read_record()?.is_some() {}
+        println!("valid {}", reader.valid);
+        println!("corrupt {}", reader.corrupt);
+        println!("recovered {}", reader.recovered);
+        return Ok(());
+    }
+
     if let Some(device) = opts.device {
         let device = Capture::from_device(device.as_str())?
             .buffer_size(opts.buffer_size as i32)
@@ -43,19 +160,76 @@ async fn main() -> Result<()> {
             .immediate_mode(opts.immediate)
             .open()?;
         let mut device = Async::new(device.setnonblock()?)?;
-        if let Some(o) = opts.output.as_ref() {
+        if opts.output.is_some() || opts.vcd.is_some() || opts.serve.is_some() || opts.framed.is_some() {
             let (tx, mut rx) = async_channel::bounded(100);
             ctrlc::set_handler(move || {
                 tx.try_send(()).ok();
             })?;
 
-            let mut output = device.get_ref().savefile(&o)?;
-            println!("writing to {}", o.display());
+            let mut output = match opts.output.as_ref() {
+                Some(o) => {
+                    println!("writing to {}", o.display());
+                    Some(device.get_ref().savefile(&o)?)
+                }
+                None => None,
+            };
+            let mut framed = match opts.framed.as_ref() {
+                Some(f) => {
+                    println!("archiving framed records to {}", f.display());
+                    Some(framing::Writer::new(std::io::BufWriter::new(File::create(f)?)))
+                }
+                None => None,
+            };
+            let hub = opts.serve.map(|addr| {
+                let hub = Hub::new();
+                let serving = hub.clone();
+                async_std::task::spawn(async move {
+                    if let Err(err) = serve(addr, serving).await {
+                        eprintln!("serve error: {}", err);
+                    }
+                });
+                hub
+            });
+            let mut vcd_tx = if opts.vcd.is_some() || hub.is_some() {
+                let sink: Sink = match (opts.vcd.as_ref(), hub.clone()) {
+                    (Some(v), Some(hub)) => {
+                        println!("writing vcd to {} and serving it live", v.display());
+                        Box::new(Tee::new(File::create(v)?, hub))
+                    }
+                    (Some(v), None) => {
+                        println!("writing vcd to {}", v.display());
+                        Box::new(File::create(v)?)
+                    }
+                    (None, Some(hub)) => Box::new(hub),
+                    (None, None) => unreachable!(),
+                };
+                let sink = VcdSink::open(sink)?;
+                let (tx, rx) = ring(VCD_RING_CAPACITY);
+                let task = async_std::task::spawn(sink.run(rx));
+                Some((tx, task))
+            } else {
+                None
+            };
             loop {
                 futures::select! {
                     ev = device.read_with_mut(|device| match device.next() {
                         Ok(packet) => {
-                            output.write(&packet);
+                            if let Some(output) = output.as_mut() {
+                                output.write(&packet);
+                            }
+                            if vcd_tx.is_some() || framed.is_some() {
+                                let ts = &packet.header.ts;
+                                let t_us = ts.tv_sec as u64 * 1_000_000 + ts.tv_usec as u64;
+                                let record = encode_record(t_us, packet.header.len, &packet.data);
+                                if let Some((tx, _)) = vcd_tx.as_mut() {
+                                    tx.push(&record);
+                                }
+                                if let Some(framed) = framed.as_mut() {
+                                    if let Err(err) = framed.write_record(&record) {
+                                        eprintln!("framed archive write error: {}", err);
+                                    }
+                                }
+                            }
                             Ok(Some(packet.data.len()))
                         }
                         Err(Error::IoError(io::ErrorKind::Interrupted)) |
@@ -76,6 +250,11 @@ async fn main() -> Result<()> {
                     _ = rx.next().fuse() => break,
                 }
             }
+            if let Some((tx, task)) = vcd_tx {
+                println!("vcd overflowed {}", tx.overflowed());
+                drop(tx);
+                task.await?;
+            }
         } else if let Some(i) = opts.input.as_ref() {
             let mut input = Capture::from_file(i)?;
             println!("reading from {}", i.display());
@@ -92,7 +271,7 @@ async fn main() -> Result<()> {
                 }
             }
         } else {
-            anyhow::bail!("required input or output");
+            anyhow::bail!("required input, output, vcd, serve or framed");
         }
         let stats = device.get_mut().stats()?;
         println!("received {}", stats.received);
@@ -0,0 +1,281 @@
+//! A fixed-capacity, single-producer/single-consumer ring buffer for handing
+//! packet bytes from the pcap read callback to a writer task without
+//! blocking. `Writer::push` copies each record into one preallocated arena
+//! and never allocates; `Reader::pop` hands each record back as a newly
+//! allocated `Vec`.
+use futures::task::AtomicWaker;
+use std::cell::UnsafeCell;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::Poll;
+
+const HEADER: usize = 4;
+const SKIP: u32 = u32::MAX;
+
+struct Shared {
+    buf: Box<[UnsafeCell<u8>]>,
+    start: AtomicUsize,
+    end: AtomicUsize,
+    overflow: AtomicUsize,
+    closed: AtomicBool,
+    waker: AtomicWaker,
+}
+
+// SAFETY: the only interior mutability is through `slice_mut`, and the
+// push/pop protocol below never hands the producer and the consumer
+// overlapping `[at, at + len)` ranges at the same time.
+unsafe impl Sync for Shared {}
+
+impl Shared {
+    fn len(&self) -> usize {
+        self.buf.len()
+    }
+
+    // SAFETY: callers only ever request the exact `[at, at + len)` range
+    // they've reserved against the consumer-visible `start` (producer) or
+    // already-published region bounded by `end` (consumer). Producer and
+    // consumer ranges never overlap, so the two sides never hold aliasing
+    // `&mut` slices even though they share one allocation.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn slice_mut(&self, at: usize, len: usize) -> &mut [u8] {
+        let base = self.buf.as_ptr() as *mut u8;
+        std::slice::from_raw_parts_mut(base.add(at), len)
+    }
+}
+
+/// The producer half of a [`ring`] channel.
+pub struct Writer {
+    shared: Arc<Shared>,
+}
+
+/// The consumer half of a [`ring`] channel.
+pub struct Reader {
+    shared: Arc<Shared>,
+}
+
+/// Creates a ring buffer with `capacity` bytes of backing storage.
+pub fn ring(capacity: usize) -> (Writer, Reader) {
+    let shared = Arc::new(Shared {
+        buf: (0..capacity).map(|_| UnsafeCell::new(0u8)).collect(),
+        start: AtomicUsize::new(0),
+        end: AtomicUsize::new(0),
+        overflow: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        waker: AtomicWaker::new(),
+    });
+    (
+        Writer {
+            shared: shared.clone(),
+        },
+        Reader { shared },
+    )
+}
+
+impl Writer {
+    /// Copies `data` into the ring and wakes a waiting reader. Returns
+    /// `false` and bumps the overflow counter if there isn't room, which is
+    /// tracked separately from libpcap's own interface-level drop counter.
+    pub fn push(&mut self, data: &[u8]) -> bool {
+        let shared = &*self.shared;
+        let len = shared.len();
+        let start = shared.start.load(Ordering::Acquire);
+        let end = shared.end.load(Ordering::Relaxed);
+        let needed = HEADER + data.len();
+
+        // A record is never split across the physical end of the arena.
+        // If the tail is too small to even hold a header, it's a dead zone
+        // both sides silently skip; otherwise a sentinel length marks it.
+        // Either way the whole tail is wasted space that must be charged
+        // against `free`, not just the one header `push` is about to write.
+        let tail = len - end;
+        let (pos, skip_tail, wasted) = if tail >= HEADER && tail < needed {
+            (0, true, tail)
+        } else if tail < HEADER {
+            (0, false, tail)
+        } else {
+            (end, false, 0)
+        };
+
+        let free = available(start, end, len);
+        if wasted + needed > free {
+            shared.overflow.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        if skip_tail {
+            unsafe { shared.slice_mut(end, HEADER) }.copy_from_slice(&SKIP.to_le_bytes());
+        }
+        unsafe { shared.slice_mut(pos, HEADER) }
+            .copy_from_slice(&(data.len() as u32).to_le_bytes());
+        unsafe { shared.slice_mut(pos + HEADER, data.len()) }.copy_from_slice(data);
+
+        let new_end = wrap(pos + needed, len);
+        shared.end.store(new_end, Ordering::Release);
+        shared.waker.wake();
+        true
+    }
+
+    /// Number of records dropped because the ring had no room.
+    pub fn overflowed(&self) -> usize {
+        self.shared.overflow.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+        self.shared.waker.wake();
+    }
+}
+
+impl Reader {
+    /// Copies out the next available record without blocking.
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let shared = &*self.shared;
+        let len = shared.len();
+        let mut start = shared.start.load(Ordering::Relaxed);
+        let end = shared.end.load(Ordering::Acquire);
+        if start == end {
+            return None;
+        }
+        if len - start < HEADER {
+            start = 0;
+        }
+        let read_header = |at: usize| -> u32 {
+            let mut header = [0u8; HEADER];
+            header.copy_from_slice(unsafe { shared.slice_mut(at, HEADER) });
+            u32::from_le_bytes(header)
+        };
+        let start = if read_header(start) == SKIP { 0 } else { start };
+        let record_len = read_header(start) as usize;
+        let data = unsafe { shared.slice_mut(start + HEADER, record_len) }.to_vec();
+        shared
+            .start
+            .store(wrap(start + HEADER + record_len, len), Ordering::Release);
+        Some(data)
+    }
+
+    /// Awaits the next record, resolving to `None` once the writer has been
+    /// dropped and the ring has been fully drained.
+    pub async fn recv(&mut self) -> Option<Vec<u8>> {
+        futures::future::poll_fn(|cx| {
+            if let Some(data) = self.pop() {
+                return Poll::Ready(Some(data));
+            }
+            if self.shared.closed.load(Ordering::Acquire) {
+                // A record may have been published between the `pop` above
+                // and the writer closing; take it before reporting the end.
+                return Poll::Ready(self.pop());
+            }
+            self.shared.waker.register(cx.waker());
+            // Re-check after registering to avoid missing a push/close
+            // that happened between the first check and the register.
+            if let Some(data) = self.pop() {
+                return Poll::Ready(Some(data));
+            }
+            if self.shared.closed.load(Ordering::Acquire) {
+                return Poll::Ready(self.pop());
+            }
+            Poll::Pending
+        })
+        .await
+    }
+}
+
+fn wrap(i: usize, len: usize) -> usize {
+    if i >= len {
+        i - len
+    } else {
+        i
+    }
+}
+
+fn available(start: usize, end: usize, len: usize) -> usize {
+    if end >= start {
+        len - (end - start) - 1
+    } else {
+        start - end - 1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_pop_roundtrip() {
+        let (mut w, mut r) = ring(64);
+        assert!(w.push(b"hello"));
+        assert_eq!(r.pop(), Some(b"hello".to_vec()));
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn empty_ring_pops_none() {
+        let (_w, mut r) = ring(16);
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn wraps_with_sentinel_when_tail_fits_header_but_not_record() {
+        let (mut w, mut r) = ring(20);
+        assert!(w.push(&[1, 2]));
+        assert!(w.push(&[3, 4, 5, 6, 7, 8]));
+        assert_eq!(r.pop(), Some(vec![1, 2]));
+        // Tail is now 4 bytes: enough for a header (the sentinel) but not
+        // the 5-byte record, so `push` must sentinel-skip the tail and
+        // wrap the record to the front of the arena.
+        assert!(w.push(&[9]));
+        assert_eq!(r.pop(), Some(vec![3, 4, 5, 6, 7, 8]));
+        assert_eq!(r.pop(), Some(vec![9]));
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn skips_dead_tail_too_small_for_a_header() {
+        let (mut w, mut r) = ring(20);
+        assert!(w.push(&[0; 13]));
+        assert_eq!(r.pop(), Some(vec![0; 13]));
+        // Tail is now 3 bytes: too small even for a header, so `push` must
+        // jump straight to the front without writing a sentinel.
+        assert!(w.push(&[42]));
+        assert_eq!(r.pop(), Some(vec![42]));
+        assert_eq!(r.pop(), None);
+    }
+
+    #[test]
+    fn rejects_push_that_would_clobber_unread_tail_bytes() {
+        // Regression test: len=100, start=10, end=90, pushing an 11-byte
+        // record used to pass the `HEADER + needed > free` check, which
+        // charged one header for the wrap instead of the whole 10-byte tail,
+        // so the record wrapped to offset 0 and clobbered the still-unread
+        // data from `start` on.
+        let (mut w, _r) = ring(100);
+        w.shared.start.store(10, Ordering::Relaxed);
+        w.shared.end.store(90, Ordering::Relaxed);
+        let sentinel = 0xAB;
+        for b in unsafe { w.shared.slice_mut(10, 80) } {
+            *b = sentinel;
+        }
+
+        assert!(!w.push(&[0u8; 11]));
+        assert_eq!(w.overflowed(), 1);
+        assert!(unsafe { w.shared.slice_mut(10, 80) }
+            .iter()
+            .all(|&b| b == sentinel));
+    }
+
+    #[test]
+    fn available_reserves_one_byte_to_disambiguate_full_from_empty() {
+        assert_eq!(available(0, 0, 16), 15);
+        assert_eq!(available(0, 10, 16), 5);
+        assert_eq!(available(10, 0, 16), 9);
+    }
+
+    #[test]
+    fn wrap_only_subtracts_len_once() {
+        assert_eq!(wrap(5, 16), 5);
+        assert_eq!(wrap(16, 16), 0);
+        assert_eq!(wrap(20, 16), 4);
+    }
+}
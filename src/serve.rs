@@ -0,0 +1,180 @@
+//! Live-streams a running capture's VCD output to TCP clients so it can be
+//! watched while it's being written, not just replayed from disk afterwards.
+use async_std::net::{SocketAddr, TcpListener, TcpStream};
+use async_std::task;
+use futures::io::AsyncWriteExt;
+use futures::stream::StreamExt;
+use std::collections::HashMap;
+use std::io::{Result, Write};
+use std::sync::{Arc, Mutex};
+
+/// Records queued per client before it's dropped instead of stalling the
+/// capture on a slow reader.
+const CLIENT_BACKLOG: usize = 256;
+
+/// A `Write` sink that fans out every byte it receives to connected TCP
+/// clients, caching the `$var` declarations and the most recent value of
+/// every variable so a late-joining client can be brought up to date with a
+/// synthesized `$dumpvars` snapshot instead of replaying the whole history.
+#[derive(Clone)]
+pub struct Hub {
+    state: Arc<Mutex<HubState>>,
+}
+
+struct HubState {
+    pending: Vec<u8>,
+    definitions: Vec<u8>,
+    values: HashMap<Vec<u8>, Vec<u8>>,
+    clients: Vec<async_channel::Sender<Vec<u8>>>,
+}
+
+impl Hub {
+    pub fn new() -> Self {
+        Self {
+            state: Arc::new(Mutex::new(HubState {
+                pending: Vec::new(),
+                definitions: Vec::new(),
+                values: HashMap::new(),
+                clients: Vec::new(),
+            })),
+        }
+    }
+
+    /// Registers a new client, returning the bytes it should be sent
+    /// immediately (declarations plus a synthesized snapshot of every
+    /// variable's current value) and a receiver for the live tail.
+    fn subscribe(&self) -> (Vec<u8>, async_channel::Receiver<Vec<u8>>) {
+        let mut state = self.state.lock().unwrap();
+        let mut initial = state.definitions.clone();
+        if !state.values.is_empty() {
+            initial.extend_from_slice(b"$dumpvars\n");
+            for line in state.values.values() {
+                initial.extend_from_slice(line);
+            }
+            initial.extend_from_slice(b"$end\n");
+        }
+        let (tx, rx) = async_channel::bounded(CLIENT_BACKLOG);
+        state.clients.push(tx);
+        (initial, rx)
+    }
+}
+
+impl Default for Hub {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HubState {
+    fn ingest(&mut self, buf: &[u8]) {
+        self.pending.extend_from_slice(buf);
+        while let Some(pos) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=pos).collect();
+            self.handle_line(&line);
+        }
+    }
+
+    // `$timescale`/`$scope`/`$upscope`/`$var`/`$enddefinitions` only ever
+    // appear once, during the declaration phase, so they're cached
+    // verbatim for late joiners. Everything else is either a value change
+    // (cached by its identifier code so the latest one wins), a `#`
+    // timestamp, or a `$dumpvars`/`$end`/`$comment` marker; timestamps and
+    // markers are only sent live.
+    fn handle_line(&mut self, line: &[u8]) {
+        const DECLARATIONS: &[&[u8]] = &[
+            b"$timescale",
+            b"$scope",
+            b"$upscope",
+            b"$var",
+            b"$enddefinitions",
+        ];
+        if DECLARATIONS.iter().any(|kw| line.starts_with(kw)) {
+            self.definitions.extend_from_slice(line);
+        } else if let Some(key) = value_key(line) {
+            self.values.insert(key, line.to_vec());
+        }
+        self.broadcast(line);
+    }
+
+    fn broadcast(&mut self, line: &[u8]) {
+        self.clients
+            .retain(|tx| tx.try_send(line.to_vec()).is_ok());
+    }
+}
+
+/// Extracts the identifier code a value-change line ends with, or `None`
+/// if `line` isn't a value change at all (e.g. a `$`-prefixed directive).
+fn value_key(line: &[u8]) -> Option<Vec<u8>> {
+    match *line.first()? {
+        b'0' | b'1' | b'x' | b'X' | b'z' | b'Z' => Some(line[1..].to_vec()),
+        b'b' | b'B' | b'r' | b'R' | b's' | b'S' => {
+            let idx = line.iter().rposition(|&b| b == b' ')?;
+            Some(line[idx + 1..].to_vec())
+        }
+        _ => None,
+    }
+}
+
+impl Write for Hub {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.state.lock().unwrap().ingest(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes every byte to both `a` and `b`, e.g. to persist a capture to disk
+/// while also live-streaming it through a [`Hub`].
+pub struct Tee<A: Write, B: Write> {
+    a: A,
+    b: B,
+}
+
+impl<A: Write, B: Write> Tee<A, B> {
+    pub fn new(a: A, b: B) -> Self {
+        Self { a, b }
+    }
+}
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.a.write_all(buf)?;
+        self.b.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.a.flush()?;
+        self.b.flush()
+    }
+}
+
+/// Accepts connections on `addr` and streams `hub`'s VCD output to each one
+/// until the capture ends.
+pub async fn serve(addr: SocketAddr, hub: Hub) -> Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("serving vcd on {}", listener.local_addr()?);
+    let mut incoming = listener.incoming();
+    while let Some(stream) = incoming.next().await {
+        let hub = hub.clone();
+        task::spawn(async move {
+            if let Err(err) = serve_client(stream?, hub).await {
+                eprintln!("vcd client error: {}", err);
+            }
+            Result::Ok(())
+        });
+    }
+    Ok(())
+}
+
+async fn serve_client(mut stream: TcpStream, hub: Hub) -> Result<()> {
+    let (initial, rx) = hub.subscribe();
+    stream.write_all(&initial).await?;
+    while let Ok(line) = rx.recv().await {
+        stream.write_all(&line).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Best-effort decoding of a captured Ethernet frame into a short summary
+//! string, used to drive an `add_protocol` VCD variable.
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const IPPROTO_TCP: u8 = 6;
+const IPPROTO_UDP: u8 = 17;
+
+pub fn summarize(data: &[u8]) -> String {
+    if data.len() < 14 {
+        return "ETH truncated".into();
+    }
+    let dst = mac(&data[0..6]);
+    let src = mac(&data[6..12]);
+    let ethertype = u16::from_be_bytes([data[12], data[13]]);
+    if ethertype != ETHERTYPE_IPV4 || data.len() < 14 + 20 {
+        return format!("ETH {}\u{2192}{}", src, dst);
+    }
+    let ip = &data[14..];
+    let ihl = (ip[0] & 0x0f) as usize * 4;
+    if ip.len() < ihl {
+        return format!("ETH {}\u{2192}{}", src, dst);
+    }
+    let proto = ip[9];
+    let l4 = &ip[ihl..];
+    match proto {
+        IPPROTO_TCP if l4.len() >= 14 => {
+            let dport = u16::from_be_bytes([l4[2], l4[3]]);
+            format!("TCP :{} {}", dport, tcp_flags(l4[13]))
+        }
+        IPPROTO_UDP if l4.len() >= 4 => {
+            let dport = u16::from_be_bytes([l4[2], l4[3]]);
+            format!("UDP :{}", dport)
+        }
+        _ => format!("ETH {}\u{2192}{}", src, dst),
+    }
+}
+
+fn mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+fn tcp_flags(flags: u8) -> &'static str {
+    if flags & 0x02 != 0 && flags & 0x10 != 0 {
+        "SYN ACK"
+    } else if flags & 0x02 != 0 {
+        "SYN"
+    } else if flags & 0x01 != 0 {
+        "FIN"
+    } else if flags & 0x04 != 0 {
+        "RST"
+    } else {
+        "ACK"
+    }
+}
@@ -0,0 +1,264 @@
+//! Minimal builder around the `vcd` crate for streaming signal dumps.
+use std::any::Any;
+use std::io::{IoSlice, Result, Write};
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+use vcd::{IdCode, SimulationCommand, TimescaleUnit, Value, VarType};
+
+/// Bytes of scratch space buffered before [`Buffered`] flushes eagerly,
+/// independent of the `timestamp()`-boundary flush.
+const FLUSH_CAPACITY: usize = 64 * 1024;
+
+/// Ticks buffered before [`DumpVars::timestamp`] forces a flush of its own,
+/// independent of `FLUSH_CAPACITY`. A capture that opens a new tick for
+/// nearly every packet (as `--vcd` does, since pcap timestamps are
+/// microsecond-granular) would otherwise flush, and so `write_vectored`,
+/// once per packet — exactly the "millions of tiny writes" buffering is
+/// meant to collapse. Capping how often the tick boundary itself triggers a
+/// flush lets several ticks' worth of changes batch into one write while
+/// still bounding how much unflushed data (and `--serve` live-stream
+/// latency) can build up between flushes.
+const TICK_FLUSH_INTERVAL: u32 = 256;
+
+/// A cloneable `Write` sink that coalesces many small writes into a single
+/// `write_vectored` call, so a capture producing millions of tiny VCD
+/// change records doesn't issue millions of tiny syscalls.
+///
+/// Every [`Write::write`] copies its bytes into a scratch arena and records
+/// the byte range it occupied; [`Buffered::flush`] turns those ranges into
+/// `IoSlice`s only at flush time (never held across a mutation of the
+/// arena, which would be self-referential) and hands them to the
+/// underlying sink with `write_vectored`, re-issuing the remainder on a
+/// short write.
+pub struct Buffered<W: Write> {
+    inner: Arc<Mutex<BufferedInner<W>>>,
+}
+
+struct BufferedInner<W: Write> {
+    sink: W,
+    scratch: Vec<u8>,
+    bounds: Vec<usize>,
+}
+
+impl<W: Write> Buffered<W> {
+    fn new(sink: W) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(BufferedInner {
+                sink,
+                scratch: Vec::with_capacity(FLUSH_CAPACITY),
+                bounds: vec![0],
+            })),
+        }
+    }
+
+    pub fn flush(&self) -> Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl<W: Write> Clone for Buffered<W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+impl<W: Write> Write for Buffered<W> {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.scratch.extend_from_slice(buf);
+        inner.bounds.push(inner.scratch.len());
+        if inner.scratch.len() >= FLUSH_CAPACITY {
+            inner.flush()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.lock().unwrap().flush()
+    }
+}
+
+impl<W: Write> BufferedInner<W> {
+    fn flush(&mut self) -> Result<()> {
+        let total = self.scratch.len();
+        let mut consumed = 0;
+        // `write_vectored` has a default implementation, for sinks that
+        // don't support scatter-gather, which only ever consumes the first
+        // buffer: looping on partial progress handles that transparently,
+        // the same as a plain `write_all` loop would.
+        while consumed < total {
+            let slices: Vec<IoSlice> = self
+                .bounds
+                .windows(2)
+                .filter_map(|w| {
+                    let (start, end) = (w[0], w[1]);
+                    if end <= consumed {
+                        None
+                    } else {
+                        Some(IoSlice::new(&self.scratch[start.max(consumed)..end]))
+                    }
+                })
+                .collect();
+            let n = self.sink.write_vectored(&slices)?;
+            if n == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::WriteZero,
+                    "failed to write buffered vcd data",
+                ));
+            }
+            consumed += n;
+        }
+        self.scratch.clear();
+        self.bounds.clear();
+        self.bounds.push(0);
+        Ok(())
+    }
+}
+
+pub struct Timescale {
+    pub scale: u32,
+    pub unit: TimescaleUnit,
+}
+
+impl Timescale {
+    pub fn us(scale: u32) -> Self {
+        Self {
+            scale,
+            unit: TimescaleUnit::US,
+        }
+    }
+}
+
+/// Bit-expand `value`'s lowest `width` bits, MSB first, for use with a
+/// `Variable<Vec<Value>>` from [`Header::add_vector`].
+pub fn bits(value: u64, width: u32) -> Vec<Value> {
+    (0..width)
+        .rev()
+        .map(|i| if (value >> i) & 1 == 1 { Value::V1 } else { Value::V0 })
+        .collect()
+}
+
+pub struct Variable<T> {
+    _marker: PhantomData<T>,
+    code: IdCode,
+}
+
+impl<T> Variable<T> {
+    fn new(code: IdCode) -> Self {
+        Self {
+            _marker: Default::default(),
+            code,
+        }
+    }
+}
+
+pub struct Header<W: Write> {
+    writer: vcd::Writer<Buffered<W>>,
+    buf: Buffered<W>,
+}
+
+impl<W: Write> Header<W> {
+    pub fn new(w: W, timescale: Timescale) -> Result<Self> {
+        let buf = Buffered::new(w);
+        let mut writer = vcd::Writer::new(buf.clone());
+        writer.timescale(timescale.scale, timescale.unit)?;
+        Ok(Self { writer, buf })
+    }
+
+    pub fn start_module(&mut self, name: &str) -> Result<()> {
+        self.writer.add_module(name)
+    }
+
+    pub fn end_module(&mut self) -> Result<()> {
+        self.writer.upscope()
+    }
+
+    pub fn add_analog(&mut self, name: &str) -> Result<Variable<f64>> {
+        Ok(Variable::new(self.writer.add_var(
+            VarType::Real,
+            1,
+            name,
+            None,
+        )?))
+    }
+
+    pub fn add_digital(&mut self, name: &str) -> Result<Variable<Value>> {
+        Ok(Variable::new(self.writer.add_var(
+            VarType::Wire,
+            1,
+            name,
+            None,
+        )?))
+    }
+
+    pub fn add_vector(&mut self, name: &str, width: u32) -> Result<Variable<Vec<Value>>> {
+        Ok(Variable::new(self.writer.add_var(
+            VarType::Wire,
+            width,
+            name,
+            None,
+        )?))
+    }
+
+    pub fn add_protocol(&mut self, name: &str) -> Result<Variable<String>> {
+        Ok(Variable::new(self.writer.add_var(
+            VarType::String,
+            1,
+            name,
+            None,
+        )?))
+    }
+
+    pub fn finish(mut self) -> Result<DumpVars<W>> {
+        self.writer.enddefinitions()?;
+        self.writer.begin(SimulationCommand::Dumpvars)?;
+        Ok(DumpVars {
+            writer: self.writer,
+            buf: self.buf,
+            ticks_since_flush: 0,
+        })
+    }
+}
+
+pub struct DumpVars<W: Write> {
+    writer: vcd::Writer<Buffered<W>>,
+    buf: Buffered<W>,
+    ticks_since_flush: u32,
+}
+
+impl<W: Write> DumpVars<W> {
+    pub fn timestamp(&mut self, t: u64) -> Result<()> {
+        // Flush every `TICK_FLUSH_INTERVAL` ticks rather than on every one,
+        // so many single-packet ticks still batch into one `write_vectored`
+        // call; `FLUSH_CAPACITY` in `Buffered::write` covers the case where
+        // a single tick's changes are big enough to flush on their own.
+        self.ticks_since_flush += 1;
+        if self.ticks_since_flush >= TICK_FLUSH_INTERVAL {
+            self.buf.flush()?;
+            self.ticks_since_flush = 0;
+        }
+        self.writer.timestamp(t)
+    }
+
+    pub fn change_value<T: Any>(&mut self, var: &Variable<T>, val: &T) -> Result<()> {
+        let val = val as &dyn Any;
+        if let Some(val) = val.downcast_ref::<f64>() {
+            self.writer.change_real(var.code, *val)
+        } else if let Some(val) = val.downcast_ref::<Value>() {
+            self.writer.change_scalar(var.code, *val)
+        } else if let Some(val) = val.downcast_ref::<Vec<Value>>() {
+            self.writer.change_vector(var.code, val)
+        } else if let Some(val) = val.downcast_ref::<String>() {
+            self.writer.change_string(var.code, val)
+        } else {
+            unreachable!()
+        }
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.end()?;
+        self.buf.flush()
+    }
+}